@@ -1,5 +1,10 @@
+use std::future::Future;
+use std::pin::Pin;
 use std::ptr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 
 use libc::c_int;
 
@@ -17,6 +22,16 @@ pub struct hyper_clientconn_options {
     http1_preserve_header_case: bool,
     http1_preserve_header_order: bool,
     http2: bool,
+    protocol_auto: bool,
+    alpn: Vec<u8>,
+    http2_initial_stream_window_size: Option<u32>,
+    http2_initial_connection_window_size: Option<u32>,
+    http2_adaptive_window: Option<bool>,
+    http2_max_frame_size: Option<u32>,
+    http2_max_concurrent_reset_streams: Option<usize>,
+    http2_keep_alive_interval: Option<Duration>,
+    http2_keep_alive_timeout: Option<Duration>,
+    http2_keep_alive_while_idle: Option<bool>,
     /// Use a `Weak` to prevent cycles.
     exec: WeakExec,
 }
@@ -28,6 +43,7 @@ pub struct hyper_clientconn_options {
 /// keep-alive or HTTP/2 is used.
 pub struct hyper_clientconn {
     tx: Tx,
+    state: Arc<ConnState>,
 }
 
 enum Tx {
@@ -37,6 +53,119 @@ enum Tx {
     Http2(conn::http2::SendRequest<crate::Recv>),
 }
 
+/// State shared between a `hyper_clientconn` and the background task driving its
+/// connection, so that liveness and graceful shutdown can be observed from the FFI
+/// handle after the connection future has been handed off to the executor.
+#[derive(Default)]
+struct ConnState {
+    closed: AtomicBool,
+    closed_waker: Mutex<Option<Waker>>,
+    shutdown_requested: AtomicBool,
+    shutdown_waker: Mutex<Option<Waker>>,
+}
+
+impl ConnState {
+    fn mark_closed(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Some(waker) = self.closed_waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    fn request_shutdown(&self) {
+        self.shutdown_requested.store(true, Ordering::SeqCst);
+        if let Some(waker) = self.shutdown_waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A future that resolves once `ConnState::request_shutdown` has been called.
+struct ShutdownRequested(Arc<ConnState>);
+
+impl Future for ShutdownRequested {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Store the waker before the flag re-check, so a `request_shutdown` call
+        // that races with this poll is guaranteed to either be seen by the check
+        // below or to find the waker already in place and wake it.
+        *self.0.shutdown_waker.lock().unwrap() = Some(cx.waker().clone());
+        if self.0.shutdown_requested.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+/// A future that resolves once `ConnState::mark_closed` has been called.
+struct ConnClosed(Arc<ConnState>);
+
+impl Future for ConnClosed {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Store the waker before the flag re-check, so a `mark_closed` call that
+        // races with this poll is guaranteed to either be seen by the check below
+        // or to find the waker already in place and wake it.
+        *self.0.closed_waker.lock().unwrap() = Some(cx.waker().clone());
+        if self.0.closed.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+/// Drive a client connection's background future to completion, racing it against
+/// a graceful-shutdown request from `hyper_clientconn_graceful_shutdown` and
+/// marking `state` closed once the connection is fully done either way.
+///
+/// `graceful_shutdown` is called with the connection pinned in place once a
+/// shutdown is requested, so it can invoke the protocol-specific
+/// `Connection::graceful_shutdown` before the connection is driven to completion.
+async fn drive_connection<C>(conn: C, state: Arc<ConnState>, graceful_shutdown: impl FnOnce(Pin<&mut C>))
+where
+    C: Future<Output = crate::Result<()>>,
+{
+    futures_util::pin_mut!(conn);
+    let shutdown = ShutdownRequested(state.clone());
+    futures_util::pin_mut!(shutdown);
+    match futures_util::future::select(conn, shutdown).await {
+        futures_util::future::Either::Left(_) => {}
+        futures_util::future::Either::Right((_, mut conn)) => {
+            graceful_shutdown(conn.as_mut());
+            let _ = conn.await;
+        }
+    }
+    state.mark_closed();
+}
+
+/// The HTTP protocol version that a client connection handshake should negotiate.
+#[repr(C)]
+pub enum hyper_http_version {
+    /// Always use HTTP/1.
+    HYPER_HTTP_1 = 0,
+    /// Always use HTTP/2.
+    HYPER_HTTP_2 = 1,
+    /// Choose HTTP/1 or HTTP/2 based on the ALPN protocol set via
+    /// `hyper_clientconn_options_set_alpn`.
+    HYPER_HTTP_AUTO = 2,
+}
+
+/// Decide whether a handshake should use HTTP/2, given the options' configured
+/// protocol mode. When `protocol_auto` is set (`HYPER_HTTP_AUTO`), the decision is
+/// deferred to the ALPN protocol negotiated by the TLS layer; otherwise it falls
+/// back to the explicit `http2` flag set via `hyper_clientconn_options_http2` or
+/// `hyper_clientconn_options_set_protocol`.
+#[cfg(feature = "http2")]
+fn select_http2(protocol_auto: bool, alpn: &[u8], http2: bool) -> bool {
+    if protocol_auto {
+        alpn == b"h2"
+    } else {
+        http2
+    }
+}
+
 // ===== impl hyper_clientconn =====
 
 ffi_fn! {
@@ -54,16 +183,48 @@ ffi_fn! {
         Box::into_raw(hyper_task::boxed(async move {
             #[cfg(feature = "http2")]
             {
-            if options.http2 {
-                return conn::http2::Builder::new()
-                    .executor(options.exec.clone())
+            let use_http2 = select_http2(options.protocol_auto, &options.alpn, options.http2);
+
+            if use_http2 {
+                let mut builder = conn::http2::Builder::new();
+                builder.executor(options.exec.clone());
+
+                if let Some(window_size) = options.http2_initial_stream_window_size {
+                    builder.initial_stream_window_size(window_size);
+                }
+                if let Some(window_size) = options.http2_initial_connection_window_size {
+                    builder.initial_connection_window_size(window_size);
+                }
+                if let Some(enabled) = options.http2_adaptive_window {
+                    builder.adaptive_window(enabled);
+                }
+                if let Some(max_frame_size) = options.http2_max_frame_size {
+                    builder.max_frame_size(max_frame_size);
+                }
+                if let Some(max) = options.http2_max_concurrent_reset_streams {
+                    builder.max_concurrent_reset_streams(max);
+                }
+                if let Some(interval) = options.http2_keep_alive_interval {
+                    builder.keep_alive_interval(interval);
+                }
+                if let Some(timeout) = options.http2_keep_alive_timeout {
+                    builder.keep_alive_timeout(timeout);
+                }
+                if let Some(enabled) = options.http2_keep_alive_while_idle {
+                    builder.keep_alive_while_idle(enabled);
+                }
+
+                return builder
                     .handshake::<_, crate::Recv>(io)
                     .await
                     .map(|(tx, conn)| {
-                        options.exec.execute(Box::pin(async move {
-                            let _ = conn.await;
-                        }));
-                        hyper_clientconn { tx: Tx::Http2(tx) }
+                        let state = Arc::new(ConnState::default());
+                        options.exec.execute(Box::pin(drive_connection(
+                            conn,
+                            state.clone(),
+                            |conn| conn.graceful_shutdown(),
+                        )));
+                        hyper_clientconn { tx: Tx::Http2(tx), state }
                     });
             }
             }
@@ -76,10 +237,13 @@ ffi_fn! {
                 .handshake::<_, crate::Recv>(io)
                 .await
                 .map(|(tx, conn)| {
-                    options.exec.execute(Box::pin(async move {
-                        let _ = conn.await;
-                    }));
-                    hyper_clientconn { tx: Tx::Http1(tx) }
+                    let state = Arc::new(ConnState::default());
+                    options.exec.execute(Box::pin(drive_connection(
+                        conn,
+                        state.clone(),
+                        |conn| conn.graceful_shutdown(),
+                    )));
+                    hyper_clientconn { tx: Tx::Http1(tx), state }
                 })
         }))
     } ?= std::ptr::null_mut()
@@ -109,6 +273,74 @@ ffi_fn! {
     } ?= std::ptr::null_mut()
 }
 
+ffi_fn! {
+    /// Check whether the connection is ready to send another request.
+    ///
+    /// Returns a task that resolves once the connection can accept a new request,
+    /// whether because the underlying transport has capacity (HTTP/1 keep-alive) or
+    /// the remote peer has granted enough concurrent streams (HTTP/2). Poll this
+    /// task before calling `hyper_clientconn_send` to apply backpressure instead of
+    /// queueing requests onto a saturated connection.
+    ///
+    /// The `conn` must not be freed until this task completes, and must not be used
+    /// concurrently from another thread while it is outstanding (the same
+    /// requirement as every other function taking a `hyper_clientconn *`).
+    fn hyper_clientconn_ready(conn: *mut hyper_clientconn) -> *mut hyper_task {
+        // Validate the pointer once up front; the borrow itself must not outlive
+        // this statement. Each poll below re-derives its own short-lived `&mut`
+        // from the raw pointer instead of capturing one in the task across the
+        // `.await` (which would otherwise alias with any other FFI call touching
+        // `conn` for as long as this task stays unpolled-to-completion and unfreed).
+        let _ = non_null! { &mut *conn ?= ptr::null_mut() };
+
+        Box::into_raw(hyper_task::boxed(std::future::poll_fn(move |cx| {
+            let conn = unsafe { &mut *conn };
+            match conn.tx {
+                #[cfg(feature = "http1")]
+                Tx::Http1(ref mut tx) => {
+                    let fut = tx.ready();
+                    futures_util::pin_mut!(fut);
+                    fut.poll(cx)
+                }
+                #[cfg(feature = "http2")]
+                Tx::Http2(ref mut tx) => {
+                    let fut = tx.ready();
+                    futures_util::pin_mut!(fut);
+                    fut.poll(cx)
+                }
+            }
+        })))
+    } ?= std::ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Check whether the connection's background task has finished, meaning the
+    /// connection is dead and `conn` must not be used to send further requests.
+    ///
+    /// Returns `1` if the connection is closed, `0` if it is still live.
+    fn hyper_clientconn_is_closed(conn: *mut hyper_clientconn) -> c_int {
+        let conn = non_null! { &*conn ?= 0 };
+        conn.state.closed.load(Ordering::SeqCst) as c_int
+    }
+}
+
+ffi_fn! {
+    /// Start a graceful shutdown of the connection.
+    ///
+    /// Returns a task that resolves once any in-flight requests have completed and
+    /// the connection has closed. The `conn` must not be freed until this task
+    /// completes.
+    fn hyper_clientconn_graceful_shutdown(conn: *mut hyper_clientconn) -> *mut hyper_task {
+        let conn = non_null! { &mut *conn ?= ptr::null_mut() };
+        let state = conn.state.clone();
+        state.request_shutdown();
+
+        Box::into_raw(hyper_task::boxed(async move {
+            ConnClosed(state).await;
+        }))
+    } ?= std::ptr::null_mut()
+}
+
 ffi_fn! {
     /// Free a `hyper_clientconn *`.
     fn hyper_clientconn_free(conn: *mut hyper_clientconn) {
@@ -132,6 +364,16 @@ ffi_fn! {
             http1_preserve_header_case: false,
             http1_preserve_header_order: false,
             http2: false,
+            protocol_auto: false,
+            alpn: Vec::new(),
+            http2_initial_stream_window_size: None,
+            http2_initial_connection_window_size: None,
+            http2_adaptive_window: None,
+            http2_max_frame_size: None,
+            http2_max_concurrent_reset_streams: None,
+            http2_keep_alive_interval: None,
+            http2_keep_alive_timeout: None,
+            http2_keep_alive_while_idle: None,
             exec: WeakExec::new(),
         }))
     } ?= std::ptr::null_mut()
@@ -182,12 +424,258 @@ ffi_fn! {
 ffi_fn! {
     /// Set the whether to use HTTP2.
     ///
-    /// Pass `0` to disable, `1` to enable.
+    /// Pass `0` to disable, `1` to enable. This clears any auto-protocol mode
+    /// previously set via `hyper_clientconn_options_set_protocol(opts,
+    /// HYPER_HTTP_AUTO)`, so the explicit choice made here always takes effect.
     fn hyper_clientconn_options_http2(opts: *mut hyper_clientconn_options, enabled: c_int) -> hyper_code {
         #[cfg(feature = "http2")]
         {
             let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
             opts.http2 = enabled != 0;
+            opts.protocol_auto = false;
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(enabled);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the HTTP protocol version to negotiate during the handshake.
+    ///
+    /// Passing `HYPER_HTTP_AUTO` defers the choice until the handshake runs, picking
+    /// HTTP/2 if the ALPN protocol set via `hyper_clientconn_options_set_alpn` is
+    /// `h2`, and falling back to HTTP/1 otherwise. Passing `HYPER_HTTP_1` or
+    /// `HYPER_HTTP_2` forces that version, the same as `hyper_clientconn_options_http2`.
+    fn hyper_clientconn_options_set_protocol(opts: *mut hyper_clientconn_options, version: hyper_http_version) -> hyper_code {
+        let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+
+        match version {
+            hyper_http_version::HYPER_HTTP_1 => {
+                opts.protocol_auto = false;
+                opts.http2 = false;
+            }
+            hyper_http_version::HYPER_HTTP_2 => {
+                #[cfg(not(feature = "http2"))]
+                {
+                    return hyper_code::HYPERE_FEATURE_NOT_ENABLED;
+                }
+                #[cfg(feature = "http2")]
+                {
+                    opts.protocol_auto = false;
+                    opts.http2 = true;
+                }
+            }
+            hyper_http_version::HYPER_HTTP_AUTO => {
+                #[cfg(not(feature = "http2"))]
+                {
+                    return hyper_code::HYPERE_FEATURE_NOT_ENABLED;
+                }
+                #[cfg(feature = "http2")]
+                {
+                    opts.protocol_auto = true;
+                }
+            }
+        }
+
+        hyper_code::HYPERE_OK
+    }
+}
+
+ffi_fn! {
+    /// Set the ALPN protocol negotiated by the TLS layer, to be used when the
+    /// handshake protocol is set to `HYPER_HTTP_AUTO`.
+    ///
+    /// This does not consume the `alpn` buffer; it is copied into the options.
+    fn hyper_clientconn_options_set_alpn(opts: *mut hyper_clientconn_options, alpn: *const u8, alpn_len: usize) -> hyper_code {
+        let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+
+        if alpn_len == 0 {
+            opts.alpn.clear();
+            return hyper_code::HYPERE_OK;
+        }
+
+        let bytes = non_null! { std::slice::from_raw_parts(alpn, alpn_len) ?= hyper_code::HYPERE_INVALID_ARG };
+        opts.alpn = bytes.to_vec();
+        hyper_code::HYPERE_OK
+    }
+}
+
+ffi_fn! {
+    /// Set the initial stream-level flow control window size for HTTP/2 streams.
+    ///
+    /// Passing `0` restores the default window size.
+    fn hyper_clientconn_options_http2_initial_stream_window_size(opts: *mut hyper_clientconn_options, window_size: u32) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_initial_stream_window_size = if window_size == 0 { None } else { Some(window_size) };
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(window_size);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the initial connection-level flow control window size for HTTP/2.
+    ///
+    /// Passing `0` restores the default window size.
+    fn hyper_clientconn_options_http2_initial_connection_window_size(opts: *mut hyper_clientconn_options, window_size: u32) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_initial_connection_window_size = if window_size == 0 { None } else { Some(window_size) };
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(window_size);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set whether to use an adaptive flow control window for HTTP/2 streams and connections.
+    ///
+    /// Pass `0` to disable, `1` to enable. Enabling this overrides the initial window sizes.
+    fn hyper_clientconn_options_http2_adaptive_window(opts: *mut hyper_clientconn_options, enabled: c_int) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_adaptive_window = Some(enabled != 0);
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(enabled);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the maximum HTTP/2 frame size that can be received.
+    ///
+    /// Passing `0` restores the default frame size.
+    fn hyper_clientconn_options_http2_max_frame_size(opts: *mut hyper_clientconn_options, frame_size: u32) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_max_frame_size = if frame_size == 0 { None } else { Some(frame_size) };
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(frame_size);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the maximum number of HTTP/2 streams that can be reset concurrently without
+    /// the remote peer acknowledging them.
+    ///
+    /// Passing `0` restores the default limit.
+    fn hyper_clientconn_options_http2_max_concurrent_reset_streams(opts: *mut hyper_clientconn_options, max: usize) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_max_concurrent_reset_streams = if max == 0 { None } else { Some(max) };
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(max);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the interval, in milliseconds, between HTTP/2 keep-alive pings.
+    ///
+    /// Passing `0` disables HTTP/2 keep-alive pings.
+    fn hyper_clientconn_options_http2_keep_alive_interval(opts: *mut hyper_clientconn_options, interval_ms: u64) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_keep_alive_interval = if interval_ms == 0 {
+                None
+            } else {
+                Some(Duration::from_millis(interval_ms))
+            };
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(interval_ms);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the timeout, in milliseconds, for receiving an acknowledgement of an HTTP/2
+    /// keep-alive ping.
+    ///
+    /// If the ping is not acknowledged within this time, the connection is closed. Only
+    /// takes effect if `hyper_clientconn_options_http2_keep_alive_interval` is set.
+    ///
+    /// Passing `0` restores the default timeout.
+    fn hyper_clientconn_options_http2_keep_alive_timeout(opts: *mut hyper_clientconn_options, timeout_ms: u64) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_keep_alive_timeout = if timeout_ms == 0 {
+                None
+            } else {
+                Some(Duration::from_millis(timeout_ms))
+            };
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(timeout_ms);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set whether HTTP/2 keep-alive pings are sent while the connection is idle.
+    ///
+    /// Pass `0` to only send pings when there are open request streams (default), `1` to
+    /// send them regardless.
+    fn hyper_clientconn_options_http2_keep_alive_while_idle(opts: *mut hyper_clientconn_options, enabled: c_int) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_keep_alive_while_idle = Some(enabled != 0);
             hyper_code::HYPERE_OK
         }
 
@@ -212,3 +700,110 @@ ffi_fn! {
         hyper_code::HYPERE_OK
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::task::Wake;
+    use std::thread;
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn select_http2_auto_picks_http2_for_h2_alpn() {
+        assert!(select_http2(true, b"h2", false));
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn select_http2_auto_falls_back_to_http1_for_other_alpn() {
+        assert!(!select_http2(true, b"http/1.1", true));
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn select_http2_auto_falls_back_to_http1_for_empty_alpn() {
+        assert!(!select_http2(true, b"", true));
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn select_http2_manual_mode_ignores_alpn_and_uses_the_http2_flag() {
+        assert!(select_http2(false, b"", true));
+        assert!(!select_http2(false, b"", false));
+    }
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn poll_to_completion<F: Future<Output = ()>>(fut: F) {
+        futures_util::pin_mut!(fut);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(()) => return,
+                Poll::Pending => {
+                    // A wake-up unparks this thread before the timeout; sleeping for
+                    // the whole timeout means the wake-up was lost.
+                    let start = std::time::Instant::now();
+                    thread::park_timeout(Duration::from_secs(5));
+                    assert!(
+                        start.elapsed() < Duration::from_secs(5),
+                        "future was never woken after returning Pending"
+                    );
+                }
+            }
+        }
+    }
+
+    // Regression test for a lost-wakeup race: the setter (running on the C caller's
+    // thread) and the poller (running on the executor's thread) must not be able to
+    // interleave such that the wake-up is missed and the task hangs forever. Run many
+    // iterations with both threads synchronized to start together, so the setter and
+    // the first poll are likely to race against each other on at least some runs.
+    #[test]
+    fn shutdown_requested_does_not_lose_wakeup_to_a_racing_setter() {
+        for _ in 0..200 {
+            let state = Arc::new(ConnState::default());
+            let barrier = Arc::new(Barrier::new(2));
+
+            let poller_state = state.clone();
+            let poller_barrier = barrier.clone();
+            let poller = thread::spawn(move || {
+                poller_barrier.wait();
+                poll_to_completion(ShutdownRequested(poller_state));
+            });
+
+            barrier.wait();
+            state.request_shutdown();
+
+            poller.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn conn_closed_does_not_lose_wakeup_to_a_racing_setter() {
+        for _ in 0..200 {
+            let state = Arc::new(ConnState::default());
+            let barrier = Arc::new(Barrier::new(2));
+
+            let poller_state = state.clone();
+            let poller_barrier = barrier.clone();
+            let poller = thread::spawn(move || {
+                poller_barrier.wait();
+                poll_to_completion(ConnClosed(poller_state));
+            });
+
+            barrier.wait();
+            state.mark_closed();
+
+            poller.join().unwrap();
+        }
+    }
+}